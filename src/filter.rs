@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+/// A single node attribute value, as stored in `NodeRedis::attributes`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    Str(String),
+    Num(f64),
+}
+
+impl AttrValue {
+    /// Coerces a raw string into a numeric attribute when it parses as an
+    /// `f64`, falling back to a string attribute otherwise. Shared by
+    /// `FILTER` clause values and `ATTRS` values so the two can't drift.
+    pub fn parse(raw: &str) -> AttrValue {
+        match raw.parse::<f64>() {
+            Ok(n) => AttrValue::Num(n),
+            Err(_) => AttrValue::Str(raw.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+#[derive(Debug, Clone)]
+struct Clause {
+    field: String,
+    op: Op,
+    value: AttrValue,
+}
+
+/// A parsed `FILTER` expression, e.g. `category music AND year >= 2020`.
+///
+/// Clauses are implicitly ANDed together; every clause must match a node's
+/// attributes for the node to be admitted into the KNN result set.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    clauses: Vec<Clause>,
+}
+
+impl Predicate {
+    /// Parse a whitespace-delimited filter expression. Supported clause
+    /// shapes are `field value` (equality) and `field OP value`, where OP is
+    /// one of `= != > >= < <=`. Clauses are separated by the keyword `AND`,
+    /// matched case-insensitively.
+    pub fn parse(expr: &str) -> Result<Predicate, String> {
+        let tokens: Vec<&str> = expr.split_whitespace().collect();
+        if tokens.is_empty() {
+            return Err("FILTER expression is empty".to_string());
+        }
+
+        let mut clauses = Vec::new();
+        for chunk in tokens.split(|t| t.eq_ignore_ascii_case("AND")) {
+            match chunk {
+                [field, op, value] if is_op(op) => clauses.push(Clause {
+                    field: (*field).to_string(),
+                    op: parse_op(op).unwrap(),
+                    value: AttrValue::parse(value),
+                }),
+                [field, value] => clauses.push(Clause {
+                    field: (*field).to_string(),
+                    op: Op::Eq,
+                    value: AttrValue::parse(value),
+                }),
+                other => {
+                    return Err(format!("Malformed FILTER clause: {:?}", other));
+                }
+            }
+        }
+
+        Ok(Predicate { clauses })
+    }
+
+    /// Whether `attrs` satisfies every clause in this predicate.
+    pub fn matches(&self, attrs: &HashMap<String, AttrValue>) -> bool {
+        self.clauses.iter().all(|c| match attrs.get(&c.field) {
+            Some(actual) => compare(actual, c.op, &c.value),
+            None => false,
+        })
+    }
+}
+
+fn is_op(tok: &str) -> bool {
+    matches!(tok, "=" | "!=" | ">" | ">=" | "<" | "<=")
+}
+
+fn parse_op(tok: &str) -> Option<Op> {
+    match tok {
+        "=" => Some(Op::Eq),
+        "!=" => Some(Op::Ne),
+        ">" => Some(Op::Gt),
+        ">=" => Some(Op::Gte),
+        "<" => Some(Op::Lt),
+        "<=" => Some(Op::Lte),
+        _ => None,
+    }
+}
+
+fn compare(actual: &AttrValue, op: Op, expected: &AttrValue) -> bool {
+    match (actual, expected) {
+        (AttrValue::Num(a), AttrValue::Num(b)) => match op {
+            Op::Eq => (a - b).abs() < f64::EPSILON,
+            Op::Ne => (a - b).abs() >= f64::EPSILON,
+            Op::Gt => a > b,
+            Op::Gte => a >= b,
+            Op::Lt => a < b,
+            Op::Lte => a <= b,
+        },
+        (AttrValue::Str(a), AttrValue::Str(b)) => match op {
+            Op::Eq => a == b,
+            Op::Ne => a != b,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attrs(pairs: &[(&str, AttrValue)]) -> HashMap<String, AttrValue> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_implicit_equality_clause() {
+        let p = Predicate::parse("category music").unwrap();
+        assert!(p.matches(&attrs(&[("category", AttrValue::Str("music".to_string()))])));
+        assert!(!p.matches(&attrs(&[("category", AttrValue::Str("film".to_string()))])));
+    }
+
+    #[test]
+    fn splits_clauses_on_and_case_insensitively() {
+        let p = Predicate::parse("category music and year >= 2020").unwrap();
+        let matching = attrs(&[
+            ("category", AttrValue::Str("music".to_string())),
+            ("year", AttrValue::Num(2020.0)),
+        ]);
+        let non_matching = attrs(&[
+            ("category", AttrValue::Str("music".to_string())),
+            ("year", AttrValue::Num(2019.0)),
+        ]);
+        assert!(p.matches(&matching));
+        assert!(!p.matches(&non_matching));
+    }
+
+    #[test]
+    fn every_comparison_operator_is_supported() {
+        let cases = [
+            ("year = 2020", 2020.0, true),
+            ("year = 2020", 2021.0, false),
+            ("year != 2020", 2021.0, true),
+            ("year != 2020", 2020.0, false),
+            ("year > 2020", 2021.0, true),
+            ("year > 2020", 2020.0, false),
+            ("year >= 2020", 2020.0, true),
+            ("year >= 2020", 2019.0, false),
+            ("year < 2020", 2019.0, true),
+            ("year < 2020", 2020.0, false),
+            ("year <= 2020", 2020.0, true),
+            ("year <= 2020", 2021.0, false),
+        ];
+        for (expr, value, expected) in cases.iter().copied() {
+            let p = Predicate::parse(expr).unwrap();
+            let result = p.matches(&attrs(&[("year", AttrValue::Num(value))]));
+            assert_eq!(result, expected, "expr: {}, value: {}", expr, value);
+        }
+    }
+
+    #[test]
+    fn numeric_vs_string_type_mismatch_never_matches() {
+        let p = Predicate::parse("year = 2020").unwrap();
+        assert!(!p.matches(&attrs(&[("year", AttrValue::Str("2020".to_string()))])));
+
+        let p = Predicate::parse("category = music").unwrap();
+        assert!(!p.matches(&attrs(&[("category", AttrValue::Num(1.0))])));
+    }
+
+    #[test]
+    fn ordering_operators_are_rejected_for_strings() {
+        let p = Predicate::parse("category > music").unwrap();
+        assert!(!p.matches(&attrs(&[("category", AttrValue::Str("music".to_string()))])));
+    }
+
+    #[test]
+    fn missing_field_never_matches() {
+        let p = Predicate::parse("category music").unwrap();
+        assert!(!p.matches(&attrs(&[])));
+    }
+
+    #[test]
+    fn rejects_empty_expression() {
+        assert!(Predicate::parse("").is_err());
+        assert!(Predicate::parse("   ").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_clauses() {
+        assert!(Predicate::parse("category").is_err());
+        assert!(Predicate::parse("category music extra").is_err());
+        assert!(Predicate::parse("category ~~ music").is_err());
+    }
+
+    #[test]
+    fn attr_value_parse_coerces_numbers_and_falls_back_to_strings() {
+        assert_eq!(AttrValue::parse("2020"), AttrValue::Num(2020.0));
+        assert_eq!(AttrValue::parse("3.5"), AttrValue::Num(3.5));
+        assert_eq!(AttrValue::parse("music"), AttrValue::Str("music".to_string()));
+    }
+}
@@ -1,3 +1,17 @@
+mod filter;
+// `hnsw` and `types` ship with this crate's other commits/vendoring; their
+// surface grew across this series (chunk0-1..chunk0-7) to cover:
+//   hnsw::metrics::{cosine, inner_product}           (chunk0-1)
+//   Index::{metric, metric_name}                     (chunk0-1)
+//   Index::search_knn(..., ef, filter, now)           (chunk0-2, chunk0-3, chunk0-6)
+//   Index::add_node(..., attrs, update_node)          (chunk0-3)
+//   Index::{quantize, q_min, q_scale}                 (chunk0-4)
+//   NodeRedis::{data_q, attributes},
+//   IndexRedis::{q_min, q_scale}                      (chunk0-4)
+//   Index::name_to_id, NodeRedis::neighbor_ids,
+//   IndexRedis::{id_to_name, layer_ids, enterpoint_id} (chunk0-7)
+//   Node::expire_at, NodeRedis::expire_at              (chunk0-6)
+// See the call sites below for the exact signatures each commit relies on.
 mod hnsw;
 mod types;
 
@@ -11,10 +25,12 @@ extern crate num;
 extern crate ordered_float;
 extern crate owning_ref;
 
+use filter::{AttrValue, Predicate};
 use hnsw::{Index, Node};
 use redis_module::{
     parse_float, parse_unsigned_integer, Context, RedisError, RedisResult, RedisValue,
 };
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
 use std::convert::TryInto;
@@ -25,12 +41,46 @@ static PREFIX: &str = "hnsw";
 
 // type IndexArc = Arc<RwLock<Index<f32, f32>>>;
 type IndexT = Index<f32, f32>;
+type MetricFn = Box<dyn Fn(&[f32], &[f32]) -> f32 + Send + Sync>;
+
+fn metric_for_name(name: &str) -> Result<MetricFn, RedisError> {
+    match name {
+        "l2" | "euclidean" => Ok(Box::new(hnsw::metrics::euclidean)),
+        "cosine" => Ok(Box::new(hnsw::metrics::cosine)),
+        "ip" | "inner_product" => Ok(Box::new(hnsw::metrics::inner_product)),
+        _ => Err(RedisError::String(format!("Unknown metric: {}", name))),
+    }
+}
 
 lazy_static! {
     static ref INDICES: Arc<RwLock<HashMap<String, IndexT>>> =
         Arc::new(RwLock::new(HashMap::new()));
 }
 
+// Parses the `ATTRS` argument to hnsw.node.add: a comma-separated list of
+// `key=value` pairs, e.g. `category=music,year=2020`.
+fn parse_attrs(raw: &str) -> Result<HashMap<String, AttrValue>, RedisError> {
+    let mut attrs = HashMap::new();
+    for pair in raw.split(',') {
+        let mut kv = pair.splitn(2, '=');
+        let key = kv.next().unwrap_or("").trim();
+        let value = match kv.next() {
+            Some(v) => v.trim(),
+            None => {
+                return Err(RedisError::String(format!(
+                    "Malformed ATTRS pair: {}",
+                    pair
+                )));
+            }
+        };
+        if key.is_empty() {
+            return Err(RedisError::String(format!("Malformed ATTRS pair: {}", pair)));
+        }
+        attrs.insert(key.to_string(), AttrValue::parse(value));
+    }
+    Ok(attrs)
+}
+
 fn new_index(ctx: &Context, args: Vec<String>) -> RedisResult {
     if args.len() < 2 {
         return Err(RedisError::WrongArity);
@@ -57,6 +107,32 @@ fn new_index(ctx: &Context, args: Vec<String>) -> RedisResult {
             .unwrap_or(ef_construction);
     }
 
+    let mut metric_name = "l2".to_string();
+    let mut quantize = false;
+    let mut i = 5;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "METRIC" => {
+                if i + 1 >= args.len() {
+                    return Err(RedisError::String("METRIC requires a value".into()));
+                }
+                metric_name = args[i + 1].to_lowercase();
+                i += 2;
+            }
+            "QUANTIZE" => {
+                if i + 1 >= args.len() || !args[i + 1].eq_ignore_ascii_case("SQ8") {
+                    return Err(RedisError::String("QUANTIZE only supports SQ8".into()));
+                }
+                quantize = true;
+                i += 2;
+            }
+            other => {
+                return Err(RedisError::String(format!("Unknown argument: {}", other)));
+            }
+        }
+    }
+    let metric = metric_for_name(&metric_name)?;
+
     // write to redis
     let key = ctx.open_key_writable(&index_name);
     match key.get_value::<IndexRedis>(&HNSW_INDEX_REDIS_TYPE)? {
@@ -70,11 +146,25 @@ fn new_index(ctx: &Context, args: Vec<String>) -> RedisResult {
             // create index
             let mut index = Index::new(
                 &index_name,
-                Box::new(hnsw::metrics::euclidean),
+                metric,
                 data_dim,
                 m,
                 ef_construction,
             );
+            index.metric_name = metric_name;
+            index.quantize = quantize;
+            if quantize {
+                // SQ8 assumes vectors are roughly unit-normalized (the usual
+                // case for embeddings used with the cosine/inner-product
+                // metrics) and maps each dimension linearly from [-1, 1]
+                // onto the full i8 range. Fixed once at index creation so
+                // every node's code stays comparable to every other node's.
+                // A code c decodes to q_min + c * q_scale, so q_min is offset
+                // by 128 steps: c = -128 decodes to -1.0 and c = 127 to 1.0.
+                let scale = 2.0_f32 / 255.0;
+                index.q_min = vec![-1.0_f32 + 128.0 * scale; data_dim];
+                index.q_scale = vec![scale; data_dim];
+            }
             ctx.log_debug(format!("{:?}", index).as_str());
             key.set_value::<IndexRedis>(&HNSW_INDEX_REDIS_TYPE, (& mut index).into())?;
             // Add index to global hashmap
@@ -158,66 +248,168 @@ fn load_index<'a>(ctx: &Context, indices: &'a mut RwLockWriteGuard<HashMap<Strin
     Ok(index)
 }
 
+fn now_secs() -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+fn is_expired(expire_at: Option<i64>) -> bool {
+    match expire_at {
+        Some(t) => t <= now_secs(),
+        None => false,
+    }
+}
+
+// Maps SQ8 int8 codes back to their original-scale f32 values using the
+// per-dimension min/scale factors computed when the index was quantized.
+fn dequantize(codes: &[i8], min: &[f32], scale: &[f32]) -> Result<Vec<f32>, RedisError> {
+    if codes.len() != min.len() || codes.len() != scale.len() {
+        return Err(RedisError::String(format!(
+            "Corrupt SQ8 node: {} codes vs {} min / {} scale entries",
+            codes.len(),
+            min.len(),
+            scale.len()
+        )));
+    }
+    Ok(codes
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| min[i] + (c as f32) * scale[i])
+        .collect())
+}
+
+// The inverse of dequantize: maps full-precision values into SQ8 codes using
+// the same per-dimension min/scale the index was quantized with.
+fn quantize(data: &[f32], min: &[f32], scale: &[f32]) -> Vec<i8> {
+    data.iter()
+        .enumerate()
+        .map(|(i, &v)| (((v - min[i]) / scale[i]).round().clamp(-128.0, 127.0)) as i8)
+        .collect()
+}
+
+// Resolves each neighbor Weak ref on `node` to the u32 id persisted in
+// `name_to_id`, so NodeRedis stores compact adjacency instead of the legacy
+// node-name strings.
+fn neighbor_ids_for(name_to_id: &HashMap<String, u32>, node: &Node<f32>) -> Vec<Vec<u32>> {
+    node.read()
+        .neighbors
+        .iter()
+        .map(|layer| {
+            layer
+                .iter()
+                .filter_map(|weak| weak.upgrade())
+                .filter_map(|neighbor| name_to_id.get(&neighbor.read().name).copied())
+                .collect()
+        })
+        .collect()
+}
+
+fn node_redis_with_ids(index: &IndexT, node: &Node<f32>) -> NodeRedis {
+    let mut nr: NodeRedis = node.into();
+    nr.neighbor_ids = neighbor_ids_for(&index.name_to_id, node);
+    if index.quantize {
+        nr.data_q = quantize(&nr.data, &index.q_min, &index.q_scale);
+    }
+    nr
+}
+
+thread_local! {
+    // hnsw::Index::{add_node,delete_node} invoke `update_node` synchronously,
+    // deep inside a call that already holds `index` (and, at the top level,
+    // INDICES' write lock) mutably. The callback can't re-acquire either, and
+    // can't resolve neighbor ids without a finished, fully up-to-date
+    // `name_to_id` map, so it just records which nodes changed; the caller
+    // flushes them to redis once the mutating call returns and `index` is
+    // free to borrow again.
+    static PENDING_NODE_WRITES: RefCell<Vec<(String, Node<f32>)>> = RefCell::new(Vec::new());
+}
+
+fn update_node(name: String, node: Node<f32>) {
+    PENDING_NODE_WRITES.with(|pending| pending.borrow_mut().push((name, node)));
+}
+
+fn flush_pending_node_writes(ctx: &Context, index: &IndexT) -> Result<(), RedisError> {
+    let pending = PENDING_NODE_WRITES.with(|pending| pending.replace(Vec::new()));
+    for (name, node) in pending {
+        write_node(ctx, &name, node_redis_with_ids(index, &node))?;
+    }
+    Ok(())
+}
+
 fn make_index(ctx: &Context, ir: &IndexRedis) -> Result<IndexT, RedisError> {
     let mut index: IndexT = ir.into();
-
-    index.nodes = HashMap::with_capacity(ir.node_count);
-    for node_name in &ir.nodes {
-        let key = ctx.open_key(&node_name);
+    index.metric = metric_for_name(&ir.metric_name)?;
+
+    // ir.id_to_name is the persisted node-id -> node-name map (the
+    // counterpart of index.name_to_id used while the index is live). Neighbor
+    // adjacency and layer membership are stored as u32 ids, so reload is a
+    // single pass over compact integer arrays rather than a redis key open
+    // per string reference.
+    index.nodes = HashMap::with_capacity(ir.id_to_name.len());
+    let mut by_id: Vec<Option<Node<f32>>> = vec![None; ir.id_to_name.len()];
+    for (id, node_name) in ir.id_to_name.iter().enumerate() {
+        let key = ctx.open_key(node_name);
 
         let nr = match key.get_value::<NodeRedis>(&HNSW_NODE_REDIS_TYPE)? {
             Some(n) => n,
             None => return Err(format!("Node: {} does not exist", node_name).into()),
         };
-        let node = Node::new(node_name, &nr.data, index.m_max_0);
-        index.nodes.insert(node_name.to_owned(), node);
+        // SQ8 indices persist int8 codes plus the index-wide min/scale used
+        // to produce them; dequantize back to full precision for the graph.
+        let data = if ir.quantize {
+            dequantize(&nr.data_q, &ir.q_min, &ir.q_scale)?
+        } else {
+            nr.data.clone()
+        };
+        let node = Node::new(node_name, &data, index.m_max_0);
+        if nr.expire_at.is_some() {
+            node.write().expire_at = nr.expire_at;
+        }
+        index.nodes.insert(node_name.to_owned(), node.clone());
+        by_id[id] = Some(node);
     }
 
-    // reconstruct nodes
-    for node_name in &ir.nodes {
+    let resolve = |id: u32| -> Result<&Node<f32>, RedisError> {
+        by_id
+            .get(id as usize)
+            .and_then(|n| n.as_ref())
+            .ok_or_else(|| format!("Node id: {} does not exist", id).into())
+    };
+
+    // reconstruct neighbors
+    for node_name in &ir.id_to_name {
         let target = index.nodes.get(node_name).unwrap();
 
-        let key = ctx.open_key(&node_name);
+        let key = ctx.open_key(node_name);
 
         let nr = match key.get_value::<NodeRedis>(&HNSW_NODE_REDIS_TYPE)? {
             Some(n) => n,
             None => return Err(format!("Node: {} does not exist", node_name).into()),
         };
-        for layer in &nr.neighbors {
+        for layer in &nr.neighbor_ids {
             let mut node_layer = Vec::with_capacity(layer.len());
-            for neighbor in layer {
-                let nn = match index.nodes.get(neighbor) {
-                    Some(node) => node,
-                    None => return Err(format!("Node: {} does not exist", node_name).into()),
-                };
-                node_layer.push(nn.downgrade());
+            for &neighbor_id in layer {
+                node_layer.push(resolve(neighbor_id)?.downgrade());
             }
             target.write().neighbors.push(node_layer);
         }
     }
 
     // reconstruct layers
-    for layer in &ir.layers {
+    for layer in &ir.layer_ids {
         let mut node_layer = HashSet::with_capacity(layer.len());
-        for node_name in layer {
-            let node = match index.nodes.get(node_name) {
-                Some(n) => n,
-                None => return Err(format!("Node: {} does not exist", node_name).into()),
-            };
-            node_layer.insert(node.downgrade());
+        for &node_id in layer {
+            node_layer.insert(resolve(node_id)?.downgrade());
         }
         index.layers.push(node_layer);
     }
 
     // set enterpoint
-    index.enterpoint = match &ir.enterpoint {
-        Some(node_name) => {
-            let node = match index.nodes.get(node_name) {
-                Some(n) => n,
-                None => return Err(format!("Node: {} does not exist", node_name).into()),
-            };
-            Some(node.downgrade())
-        }
+    index.enterpoint = match ir.enterpoint_id {
+        Some(node_id) => Some(resolve(node_id)?.downgrade()),
         None => None,
     };
 
@@ -255,23 +447,81 @@ fn add_node(ctx: &Context, args: Vec<String>) -> RedisResult {
     let index_name = format!("{}.{}", PREFIX, &args[1]);
     let node_name = format!("{}.{}.{}", PREFIX, &args[1], &args[2]);
 
-    let dataf64 = &args[3..]
+    let mut indices = INDICES.write().unwrap();
+    let index = load_index(ctx, & mut indices, &index_name)?;
+
+    // The vector always comes right after <index> <name>; ATTRS/TTL/EX/EXAT/
+    // PERSIST are a trailing suffix (unlike hnsw.search, whose EF/FILTER
+    // come before the query vector). The index must already be loaded to
+    // know how many components to take, since the vector has no terminator
+    // of its own.
+    if args.len() < 3 + index.dims {
+        return Err(RedisError::String(format!(
+            "hnsw.node.add expects {} vector components",
+            index.dims
+        )));
+    }
+    let dataf64 = &args[3..3 + index.dims]
         .iter()
         .map(|s| parse_float(s))
         .collect::<Result<Vec<f64>, RedisError>>()?;
     let data = dataf64.iter().map(|d| *d as f32).collect::<Vec<f32>>();
 
-    let mut indices = INDICES.write().unwrap();
-    let index = load_index(ctx, & mut indices, &index_name)?;
+    let mut attrs = HashMap::new();
+    let mut expire_at: Option<i64> = None;
+    let mut i = 3 + index.dims;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "ATTRS" => {
+                if i + 1 >= args.len() {
+                    return Err(RedisError::String("ATTRS requires a value".into()));
+                }
+                attrs = parse_attrs(&args[i + 1])?;
+                i += 2;
+            }
+            "TTL" | "EX" => {
+                if i + 1 >= args.len() {
+                    return Err(RedisError::String(format!("{} requires a value", &args[i])));
+                }
+                let secs = parse_unsigned_integer(&args[i + 1])? as i64;
+                expire_at = Some(now_secs() + secs);
+                i += 2;
+            }
+            "EXAT" => {
+                if i + 1 >= args.len() {
+                    return Err(RedisError::String("EXAT requires a value".into()));
+                }
+                expire_at = Some(parse_unsigned_integer(&args[i + 1])? as i64);
+                i += 2;
+            }
+            "PERSIST" => {
+                expire_at = None;
+                i += 1;
+            }
+            other => {
+                return Err(RedisError::String(format!("Unknown argument: {}", other)));
+            }
+        }
+    }
 
     ctx.log_debug(format!("Adding node: {} to Index: {}", &node_name, &index_name).as_str());
-    if let Err(e) = index.add_node(&node_name, &data, update_node) {
+    if let Err(e) = index.add_node(&node_name, &data, attrs, update_node) {
         return Err(e.error_string().into())
     }
 
+    if let Some(expiry) = expire_at {
+        let node = index.nodes.get(&node_name).unwrap();
+        node.write().expire_at = Some(expiry);
+    }
+
+    // persist the back-edge updates index.add_node queued for this node's
+    // new neighbors before resolving this node's own neighbor_ids, so both
+    // reads see a fully up-to-date name_to_id map
+    flush_pending_node_writes(ctx, index)?;
+
     // write node to redis
     let node = index.nodes.get(&node_name).unwrap();
-    write_node(ctx, &node_name, node.into())?;
+    write_node(ctx, &node_name, node_redis_with_ids(index, node))?;
 
     // update index in redis
     update_index(ctx, &index_name, & mut *index)?;
@@ -315,12 +565,167 @@ fn delete_node(ctx: &Context, args: Vec<String>) -> RedisResult {
         }
     };
 
+    // persist the back-edge updates index.delete_node queued for the
+    // deleted node's former neighbors
+    flush_pending_node_writes(ctx, index)?;
+
     // update index in redis
     update_index(ctx, &index_name, & mut *index)?;
 
     Ok(1_usize.into())
 }
 
+fn madd_node(ctx: &Context, args: Vec<String>) -> RedisResult {
+    if args.len() < 4 {
+        return Err(RedisError::WrongArity);
+    }
+
+    ctx.auto_memory();
+
+    let index_name = format!("{}.{}", PREFIX, &args[1]);
+
+    let mut indices = INDICES.write().unwrap();
+    let index = load_index(ctx, & mut indices, &index_name)?;
+    let group_size = index.dims + 1;
+
+    let groups = &args[2..];
+    if groups.is_empty() || groups.len() % group_size != 0 {
+        return Err(RedisError::String(
+            "hnsw.node.madd expects <name> <vec...> groups matching the index dimension".into(),
+        ));
+    }
+
+    // Parse the whole batch up front, like mdel_node validates its whole
+    // batch up front, so a malformed group can't leave earlier groups
+    // mutated in the in-memory graph without ever being persisted to redis.
+    let mut parsed = Vec::with_capacity(groups.len() / group_size);
+    for group in groups.chunks(group_size) {
+        let node_name = format!("{}.{}.{}", PREFIX, &args[1], &group[0]);
+        let dataf64 = group[1..]
+            .iter()
+            .map(|s| parse_float(s))
+            .collect::<Result<Vec<f64>, RedisError>>()?;
+        let data = dataf64.iter().map(|d| *d as f32).collect::<Vec<f32>>();
+        parsed.push((node_name, data));
+    }
+
+    // Mutate and persist one node at a time, including the index itself
+    // (layers/name_to_id/enterpoint), mirroring what a single hnsw.node.add
+    // call does for its one node. If add_node fails partway through the
+    // batch, every group before it is already fully persisted — node key
+    // and index both — so nothing is left mutated-but-unpersisted or
+    // orphaned in redis.
+    let mut node_names = Vec::with_capacity(parsed.len());
+    for (node_name, data) in &parsed {
+        ctx.log_debug(format!("Adding node: {} to Index: {}", node_name, &index_name).as_str());
+        if let Err(e) = index.add_node(node_name, data, HashMap::new(), update_node) {
+            return Err(e.error_string().into());
+        }
+
+        flush_pending_node_writes(ctx, index)?;
+
+        let node = index.nodes.get(node_name).unwrap();
+        write_node(ctx, node_name, node_redis_with_ids(index, node))?;
+        node_names.push(node_name.clone());
+
+        update_index(ctx, &index_name, & mut *index)?;
+    }
+
+    Ok((node_names.len() as i64).into())
+}
+
+fn mdel_node(ctx: &Context, args: Vec<String>) -> RedisResult {
+    if args.len() < 3 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let index_name = format!("{}.{}", PREFIX, &args[1]);
+    let mut indices = INDICES.write().unwrap();
+    let index = load_index(ctx, & mut indices, &index_name)?;
+
+    // Resolve and validate the whole batch before mutating anything, so a
+    // single in-use node aborts the call instead of partially deleting it.
+    let mut node_names = Vec::with_capacity(args.len() - 2);
+    for name in &args[2..] {
+        let node_name = format!("{}.{}.{}", PREFIX, &args[1], name);
+        let node = index
+            .nodes
+            .get(&node_name)
+            .ok_or_else(|| format!("Node: {} does not exist", &node_name))?;
+        if Arc::strong_count(&node.0) > 1 {
+            return Err(format!(
+                "{} is being accessed, unable to delete. Try again later",
+                &node_name
+            )
+            .into());
+        }
+        node_names.push(node_name);
+    }
+
+    for node_name in &node_names {
+        if let Err(e) = index.delete_node(node_name, update_node) {
+            return Err(e.error_string().into());
+        }
+
+        ctx.log_debug(format!("del key: {}", node_name).as_str());
+        let rkey = ctx.open_key_writable(node_name);
+        rkey.delete()?;
+    }
+
+    flush_pending_node_writes(ctx, index)?;
+
+    // update index in redis once for the whole batch
+    update_index(ctx, &index_name, & mut *index)?;
+
+    Ok((node_names.len() as i64).into())
+}
+
+// Lazy expiry (hnsw.node.get, hnsw.search) only hides expired nodes from
+// reads; it never deletes them, so they'd otherwise linger forever as
+// neighbors and enterpoint candidates. This sweeps an index for nodes whose
+// TTL has passed and reaps each one through the same index.delete_node +
+// update_index path hnsw.node.del uses, repairing the graph instead of just
+// masking the node. There's no background timer in this module, so run it
+// on a schedule (e.g. cron) to get the eviction a timer would otherwise do.
+fn evict_expired(ctx: &Context, args: Vec<String>) -> RedisResult {
+    if args.len() < 2 {
+        return Err(RedisError::WrongArity);
+    }
+
+    let index_name = format!("{}.{}", PREFIX, &args[1]);
+    let mut indices = INDICES.write().unwrap();
+    let index = load_index(ctx, & mut indices, &index_name)?;
+
+    let expired: Vec<String> = index
+        .nodes
+        .iter()
+        .filter(|(_, node)| is_expired(node.read().expire_at))
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    let mut evicted = Vec::with_capacity(expired.len());
+    for node_name in &expired {
+        let node = index.nodes.get(node_name).unwrap();
+        if Arc::strong_count(&node.0) > 1 {
+            // in use; leave it for the next sweep rather than fail the whole batch
+            continue;
+        }
+        if let Err(e) = index.delete_node(node_name, update_node) {
+            return Err(e.error_string().into());
+        }
+
+        ctx.log_debug(format!("evict expired key: {}", node_name).as_str());
+        let rkey = ctx.open_key_writable(node_name);
+        rkey.delete()?;
+        evicted.push(node_name.clone());
+    }
+
+    flush_pending_node_writes(ctx, index)?;
+    update_index(ctx, &index_name, & mut *index)?;
+
+    Ok((evicted.len() as i64).into())
+}
+
 fn get_node(ctx: &Context, args: Vec<String>) -> RedisResult {
     if args.len() < 3 {
         return Err(RedisError::WrongArity);
@@ -332,8 +737,8 @@ fn get_node(ctx: &Context, args: Vec<String>) -> RedisResult {
 
     let key = ctx.open_key(&node_name);
 
-    let value = match key.get_value::<NodeRedis>(&HNSW_NODE_REDIS_TYPE)? {
-        Some(node) => node.as_redisvalue(),
+    let nr = match key.get_value::<NodeRedis>(&HNSW_NODE_REDIS_TYPE)? {
+        Some(node) => node,
         None => {
             return Err(RedisError::String(format!(
                 "Node: {} does not exist",
@@ -342,7 +747,18 @@ fn get_node(ctx: &Context, args: Vec<String>) -> RedisResult {
         }
     };
 
-    Ok(value)
+    // hnsw.node.get is readonly, so an expired node is only reported as
+    // missing here, same as hnsw.search skipping expired candidates — it
+    // does not mutate anything. Run hnsw.node.evictexpired to actually
+    // delete expired nodes (neighbor back-edges and all) from the index.
+    if is_expired(nr.expire_at) {
+        return Err(RedisError::String(format!(
+            "Node: {} does not exist",
+            &node_name
+        )));
+    }
+
+    Ok(nr.as_redisvalue())
 }
 
 fn write_node<'a>(ctx: &'a Context, key: &str, node: NodeRedis) -> RedisResult {
@@ -352,7 +768,7 @@ fn write_node<'a>(ctx: &'a Context, key: &str, node: NodeRedis) -> RedisResult {
     match rkey.get_value::<NodeRedis>(&HNSW_NODE_REDIS_TYPE)? {
         Some(value) => {
             value.data = node.data;
-            value.neighbors = node.neighbors;
+            value.neighbor_ids = node.neighbor_ids;
         }
         None => {
             rkey.set_value(&HNSW_NODE_REDIS_TYPE, node)?;
@@ -361,13 +777,6 @@ fn write_node<'a>(ctx: &'a Context, key: &str, node: NodeRedis) -> RedisResult {
     Ok(key.into())
 }
 
-fn update_node(name: String, node: hnsw::Node<f32>) {
-    let ctx = Context::get_thread_safe_context();
-    ctx.lock();
-    write_node(&ctx, &name, (&node).into()).unwrap();
-    ctx.unlock();
-}
-
 fn search_knn(ctx: &Context, args: Vec<String>) -> RedisResult {
     if args.len() < 4 {
         return Err(RedisError::WrongArity);
@@ -375,7 +784,31 @@ fn search_knn(ctx: &Context, args: Vec<String>) -> RedisResult {
 
     let index_name = format!("{}.{}", PREFIX, &args[1]);
     let k = parse_unsigned_integer(&args[2])? as usize;
-    let dataf64 = &args[3..]
+
+    let mut ef: Option<usize> = None;
+    let mut filter: Option<Predicate> = None;
+    let mut i = 3;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "EF" => {
+                if i + 1 >= args.len() {
+                    return Err(RedisError::String("EF requires a value".into()));
+                }
+                ef = Some(parse_unsigned_integer(&args[i + 1])? as usize);
+                i += 2;
+            }
+            "FILTER" => {
+                if i + 1 >= args.len() {
+                    return Err(RedisError::String("FILTER requires a value".into()));
+                }
+                filter = Some(Predicate::parse(&args[i + 1]).map_err(RedisError::String)?);
+                i += 2;
+            }
+            _ => break,
+        }
+    }
+
+    let dataf64 = &args[i..]
         .iter()
         .map(|s| parse_float(s))
         .collect::<Result<Vec<f64>, RedisError>>()?;
@@ -384,15 +817,21 @@ fn search_knn(ctx: &Context, args: Vec<String>) -> RedisResult {
     let mut indices = INDICES.write().unwrap();
     let index = load_index(ctx, & mut indices, &index_name)?;
 
+    // EF sets the width of the dynamic candidate list for the layer-0 search;
+    // it must never be smaller than k or fewer than k results could surface.
+    let ef_search = ef.unwrap_or(index.ef_construction).max(k);
+
     ctx.log_debug(
         format!(
-            "Searching for {} nearest nodes in Index: {}",
-            k, &index_name
+            "Searching for {} nearest nodes in Index: {} (ef: {})",
+            k, &index_name, ef_search
         )
         .as_str(),
     );
 
-    match index.search_knn(&data, k) {
+    // Candidates whose TTL has already passed are skipped during traversal
+    // even if hnsw.node.evictexpired hasn't reaped them yet.
+    match index.search_knn(&data, k, ef_search, filter.as_ref(), now_secs()) {
         Ok(res) => {
             {
                 let mut reply: Vec<RedisValue> = Vec::new();
@@ -421,7 +860,10 @@ redis_module! {
         ["hnsw.del", delete_index, "write"],
         ["hnsw.search", search_knn, "readonly"],
         ["hnsw.node.add", add_node, "write"],
+        ["hnsw.node.madd", madd_node, "write"],
         ["hnsw.node.get", get_node, "readonly"],
         ["hnsw.node.del", delete_node, "write"],
+        ["hnsw.node.mdel", mdel_node, "write"],
+        ["hnsw.node.evictexpired", evict_expired, "write"],
     ],
 }